@@ -4,7 +4,7 @@ use std::{
 	borrow::Cow,
 	fs,
 	io::{self, Write},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	process, result,
 };
 
@@ -12,7 +12,13 @@ use clap::Parser;
 use regex::bytes::{Captures, Regex};
 use sewer_replacement::Replacement;
 use tempfile::{NamedTempFile, PersistError};
+mod occurrence;
 mod patchfile;
+mod pattern;
+mod walk;
+
+use occurrence::Occurrence;
+use pattern::FindPattern;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -39,19 +45,37 @@ pub enum Error {
 
 	#[error("source match was {0} bytes, but result is {1}")]
 	MismatchedLen(usize, usize),
+
+	#[error("requested occurrence {0}, but only {1} match(es) were found")]
+	OccurrenceNotFound(usize, usize),
+	#[error("occurrence index is 1-based, 0 does not refer to any match")]
+	ZeroOccurrence,
+
+	#[error("--backup is not supported together with --include")]
+	BackupWithInclude,
 }
 
 type Result<T, E = Error> = result::Result<T, E>;
 
 #[derive(Parser)]
 struct Opts {
-	/// File to patch
+	/// File to patch, or the root directory to walk when --include is given
 	file: PathBuf,
 
-	/// Copy original file to specified path
+	/// Copy original file to specified path. Not supported together with
+	/// --include, since there would be more than one original
 	#[clap(long)]
 	backup: Option<PathBuf>,
 
+	/// Instead of patching `file` itself, treat it as a root directory and
+	/// patch every file under it whose path matches this pattern. Accepts
+	/// `glob:`/`rootglob:`, `re:`/`regexp:`, or `path:` syntax; `glob:` is
+	/// assumed if no prefix is given. A file that fails to patch does not
+	/// stop the walk; it is reported and counted towards the overall
+	/// failure like a failed rule under --partial
+	#[clap(long, short = 'I')]
+	include: Option<String>,
+
 	#[clap(subcommand)]
 	command: Command,
 
@@ -66,12 +90,21 @@ struct Opts {
 pub enum Command {
 	/// Replace single occurence
 	Single {
-		/// Search regex, see syntax description here:
+		/// Search pattern: `regexp:<pattern>` (the default, also assumed
+		/// when no prefix is given), see syntax here:
 		/// https://docs.rs/regex/latest/regex/#syntax
-		find: Regex,
+		/// or `glob:<pattern>` for a byte-wildcard glob
+		find: FindPattern,
 		/// Replacement pattern, see syntax description here:
 		/// https://docs.rs/sewer-replacement/latest/sewer-replacement
 		replace: Replacement,
+
+		/// Rewrite every non-overlapping match instead of requiring exactly one
+		#[clap(long, conflicts_with = "nth")]
+		all: bool,
+		/// Rewrite only the nth match (counting from 1) instead of requiring exactly one
+		#[clap(long)]
+		nth: Option<usize>,
 	},
 
 	/// Use patchfile
@@ -94,63 +127,151 @@ fn main() {
 	}
 }
 
+/// A `Command`, loaded once (patchfiles are read and parsed up front) so it
+/// can be applied to more than one file when `--include` is in effect.
+enum Rules {
+	Single {
+		find: FindPattern,
+		replace: Replacement,
+		occurrence: Occurrence,
+	},
+	PatchFile {
+		rules: Vec<patchfile::Rule>,
+		partial: bool,
+	},
+}
+
+impl Rules {
+	fn load(command: Command) -> Result<Self> {
+		Ok(match command {
+			Command::Single {
+				find,
+				replace,
+				all,
+				nth,
+			} => {
+				let occurrence = match (all, nth) {
+					(true, _) => Occurrence::All,
+					(false, Some(n)) => Occurrence::Nth(n),
+					(false, None) => Occurrence::Single,
+				};
+				Self::Single {
+					find,
+					replace,
+					occurrence,
+				}
+			}
+			Command::PatchFile { file, partial } => {
+				let input = fs::read_to_string(file)?;
+				Self::PatchFile {
+					rules: patchfile::parse(&input)?,
+					partial,
+				}
+			}
+		})
+	}
+
+	/// Apply the rules to `data` in place. Returns whether at least one
+	/// rule matched, and whether any rule failed (only possible, and only
+	/// tolerated, in `--partial` patchfile mode).
+	fn apply(&self, data: &mut [u8], verbose: bool) -> Result<(bool, bool)> {
+		let mut has_succeded = false;
+		let mut has_failed = false;
+
+		match self {
+			Self::Single {
+				find,
+				replace,
+				occurrence,
+			} => {
+				replace_occurrence(data, &find.0, replace, *occurrence, verbose)?;
+				has_succeded = true;
+			}
+			Self::PatchFile { rules, partial } => {
+				for rule in rules {
+					if verbose {
+						eprintln!("#{}", rule.name);
+					}
+					match replace_occurrence(data, &rule.from, &rule.to, rule.occurrence, verbose) {
+						Ok(()) => has_succeded = true,
+						Err(e) if *partial => {
+							eprintln!("{e}");
+							has_failed = true;
+						}
+						Err(e) => return Err(e),
+					}
+				}
+			}
+		}
+
+		Ok((has_succeded, has_failed))
+	}
+}
+
 fn main_wrapped() -> Result<()> {
 	let mut opts = Opts::parse();
 	if opts.dry_run {
 		opts.verbose = true;
 	}
+	if opts.include.is_some() && opts.backup.is_some() {
+		return Err(Error::BackupWithInclude);
+	}
 
-	let mut data = fs::read(&opts.file)?;
-
-	let mut has_failed = false;
-	let mut has_succeded = false;
+	let rules = Rules::load(opts.command)?;
 
-	match opts.command {
-		Command::Single { find, replace } => {
-			replace_single(&mut data, find, replace, opts.verbose)?;
-		}
-		Command::PatchFile { file, partial } => {
-			let input = fs::read_to_string(file)?;
-			let rules = patchfile::parse(&input)?;
+	let has_failed = if let Some(include) = &opts.include {
+		let (syntax, pattern) = walk::parse(include);
+		let matcher = syntax.compile(pattern)?;
 
-			for rule in rules {
-				if opts.verbose {
-					eprintln!("#{}", rule.name);
-				}
-				match replace_single(&mut data, rule.from, rule.to, opts.verbose) {
-					Ok(()) => {
-						has_succeded = true;
-					}
-					Err(e) if partial => {
-						eprintln!("{e}");
-						has_failed = true;
-					}
-					Err(e) => return Err(e),
+		let mut has_failed = false;
+		for path in walk::walk(&opts.file, &matcher)? {
+			if opts.verbose {
+				eprintln!("==> {}", path.display());
+			}
+			match patch_one(&path, None, &rules, opts.dry_run, opts.verbose) {
+				Ok(failed) => has_failed |= failed,
+				Err(e) => {
+					eprintln!("{}: {e}", path.display());
+					has_failed = true;
 				}
 			}
 		}
+		has_failed
+	} else {
+		patch_one(&opts.file, opts.backup.as_deref(), &rules, opts.dry_run, opts.verbose)?
+	};
+
+	if has_failed {
+		return Err(Error::OneOrMoreRulesReturnedErrors);
 	}
 
-	if !opts.dry_run && has_succeded {
-		if let Some(backup) = opts.backup {
-			fs::rename(&opts.file, backup)?;
+	Ok(())
+}
+
+/// Read, patch and (unless `dry_run`) write back a single file. Returns
+/// whether any rule failed (tolerated only in `--partial` patchfile mode).
+fn patch_one(
+	path: &Path,
+	backup: Option<&Path>,
+	rules: &Rules,
+	dry_run: bool,
+	verbose: bool,
+) -> Result<bool> {
+	let mut data = fs::read(path)?;
+	let (has_succeded, has_failed) = rules.apply(&mut data, verbose)?;
+
+	if !dry_run && has_succeded {
+		if let Some(backup) = backup {
+			fs::rename(path, backup)?;
 		}
 
-		let mut temp = NamedTempFile::new_in(
-			opts.file
-				.parent()
-				.as_ref()
-				.expect("we already read this file"),
-		)?;
+		let mut temp =
+			NamedTempFile::new_in(path.parent().expect("we already read this file"))?;
 		temp.write_all(&data)?;
-		temp.persist(opts.file)?;
+		temp.persist(path)?;
 	}
 
-	if has_failed {
-		return Err(Error::OneOrMoreRulesReturnedErrors);
-	}
-
-	Ok(())
+	Ok(has_failed)
 }
 
 struct RegexCapture<'t>(&'t Captures<'t>);
@@ -164,7 +285,36 @@ impl<'t> sewer_replacement::Capture for RegexCapture<'t> {
 	}
 }
 
-fn replace_single(data: &mut [u8], from: Regex, to: Replacement, verbose: bool) -> Result<()> {
+fn print_rewrite(start: usize, end: usize, old: &[u8], new: &[u8]) {
+	eprintln!("@{start}..{end}");
+	eprint!("-");
+	for i in old {
+		eprint!("\\x{i:02x?}");
+	}
+	eprintln!();
+	eprint!("+");
+	for i in new {
+		eprint!("\\x{i:02x?}");
+	}
+	eprintln!();
+}
+
+/// Dispatch to the replacement implementation for `occurrence`.
+fn replace_occurrence(
+	data: &mut [u8],
+	from: &Regex,
+	to: &Replacement,
+	occurrence: Occurrence,
+	verbose: bool,
+) -> Result<()> {
+	match occurrence {
+		Occurrence::Single => replace_single(data, from, to, verbose),
+		Occurrence::Nth(n) => replace_nth(data, from, to, n, verbose),
+		Occurrence::All => replace_all(data, from, to, verbose),
+	}
+}
+
+fn replace_single(data: &mut [u8], from: &Regex, to: &Replacement, verbose: bool) -> Result<()> {
 	let (range, out) = {
 		let cap = if let Some(m) = from.captures(data) {
 			m
@@ -181,17 +331,7 @@ fn replace_single(data: &mut [u8], from: Regex, to: Replacement, verbose: bool)
 		let out = to.build(&RegexCapture(&cap))?;
 
 		if verbose {
-			eprintln!("@{}..{}", mat.start(), mat.end());
-			eprint!("-");
-			for i in mat.as_bytes() {
-				eprint!("\\x{i:02x?}");
-			}
-			eprintln!();
-			eprint!("+");
-			for i in &out {
-				eprint!("\\x{i:02x?}");
-			}
-			eprintln!();
+			print_rewrite(mat.start(), mat.end(), mat.as_bytes(), &out);
 		}
 
 		if mat.as_bytes().len() != out.len() {
@@ -204,3 +344,127 @@ fn replace_single(data: &mut [u8], from: Regex, to: Replacement, verbose: bool)
 
 	Ok(())
 }
+
+/// Rewrite only the `n`th match (1-indexed), ignoring any others.
+fn replace_nth(
+	data: &mut [u8],
+	from: &Regex,
+	to: &Replacement,
+	n: usize,
+	verbose: bool,
+) -> Result<()> {
+	if n == 0 {
+		return Err(Error::ZeroOccurrence);
+	}
+
+	let (range, out) = {
+		let mut matches = from.captures_iter(data);
+		let cap = match matches.nth(n - 1) {
+			Some(cap) => cap,
+			None => return Err(Error::OccurrenceNotFound(n, from.find_iter(data).count())),
+		};
+
+		let mat = cap.get(0).expect("full match always present");
+		let out = to.build(&RegexCapture(&cap))?;
+
+		if verbose {
+			print_rewrite(mat.start(), mat.end(), mat.as_bytes(), &out);
+		}
+
+		if mat.as_bytes().len() != out.len() {
+			return Err(Error::MismatchedLen(mat.as_bytes().len(), out.len()));
+		}
+		(mat.range(), out)
+	};
+
+	data[range].copy_from_slice(&out);
+
+	Ok(())
+}
+
+/// Rewrite every non-overlapping match.
+fn replace_all(data: &mut [u8], from: &Regex, to: &Replacement, verbose: bool) -> Result<()> {
+	let rewrites = {
+		let mut rewrites = Vec::new();
+		for cap in from.captures_iter(data) {
+			let mat = cap.get(0).expect("full match always present");
+			let out = to.build(&RegexCapture(&cap))?;
+
+			if verbose {
+				print_rewrite(mat.start(), mat.end(), mat.as_bytes(), &out);
+			}
+			if mat.as_bytes().len() != out.len() {
+				return Err(Error::MismatchedLen(mat.as_bytes().len(), out.len()));
+			}
+			rewrites.push((mat.range(), out));
+		}
+		rewrites
+	};
+
+	if rewrites.is_empty() {
+		return Err(Error::SourcePatternNotFound);
+	}
+
+	for (range, out) in rewrites {
+		data[range].copy_from_slice(&out);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn re(pattern: &str) -> Regex {
+		Regex::new(pattern).unwrap()
+	}
+
+	fn replacement(s: &str) -> Replacement {
+		Replacement::from_str(s).unwrap()
+	}
+
+	#[test]
+	fn replace_nth_rewrites_only_the_selected_match() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		replace_nth(&mut data, &re("BBB"), &replacement("yyy"), 2, false).unwrap();
+		assert_eq!(&data, b"AAABBBCCCyyyDDD");
+	}
+
+	#[test]
+	fn replace_nth_is_one_indexed_and_rejects_zero() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		let err = replace_nth(&mut data, &re("BBB"), &replacement("yyy"), 0, false).unwrap_err();
+		assert!(matches!(err, Error::ZeroOccurrence));
+	}
+
+	#[test]
+	fn replace_nth_past_the_last_match_is_an_error() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		let err = replace_nth(&mut data, &re("BBB"), &replacement("yyy"), 3, false).unwrap_err();
+		assert!(matches!(err, Error::OccurrenceNotFound(3, 2)));
+	}
+
+	#[test]
+	fn replace_all_rewrites_every_non_overlapping_match() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		replace_all(&mut data, &re("BBB"), &replacement("yyy"), false).unwrap();
+		assert_eq!(&data, b"AAAyyyCCCyyyDDD");
+	}
+
+	#[test]
+	fn replace_all_with_no_matches_is_an_error() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		let err = replace_all(&mut data, &re("ZZZ"), &replacement("yyy"), false).unwrap_err();
+		assert!(matches!(err, Error::SourcePatternNotFound));
+	}
+
+	#[test]
+	fn replace_all_rejects_a_length_changing_match() {
+		let mut data = b"AAABBBCCCBBBDDD".to_vec();
+		let err = replace_all(&mut data, &re("BBB"), &replacement("yyyy"), false).unwrap_err();
+		assert!(matches!(err, Error::MismatchedLen(3, 4)));
+	}
+}
@@ -0,0 +1,35 @@
+//! Selecting which of possibly-several matches of a rule's `from` pattern
+//! should be rewritten.
+
+use std::str::FromStr;
+
+/// Which match(es) a rule should rewrite when its `from` pattern occurs
+/// more than once in the data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrence {
+	/// Exactly one match is expected; more than one is an error.
+	#[default]
+	Single,
+	/// Rewrite every non-overlapping match.
+	All,
+	/// Rewrite only the `n`th match, counting from 1.
+	Nth(usize),
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("invalid occurrence {0:?}, expected \"all\" or a 1-based match index")]
+pub struct ParseOccurrenceError(String);
+
+impl FromStr for Occurrence {
+	type Err = ParseOccurrenceError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.eq_ignore_ascii_case("all") {
+			return Ok(Self::All);
+		}
+		match s.parse() {
+			Ok(0) | Err(_) => Err(ParseOccurrenceError(s.to_owned())),
+			Ok(n) => Ok(Self::Nth(n)),
+		}
+	}
+}
@@ -5,16 +5,60 @@ use regex::bytes::Regex;
 
 use sewer_replacement::{self, Replacement};
 
+use crate::{occurrence::Occurrence, pattern::FindPattern};
+
 struct PRule {
 	name: String,
+	/// Per-rule syntax override, from a `#name [glob]` (or `[glob,count=all]`)
+	/// suffix on the name line, overriding the file's current `syntax:`
+	/// directive.
+	syntax: Option<String>,
+	/// Per-rule occurrence selector, from a `count=<all|N>` token in the
+	/// same bracket. Defaults to `Occurrence::Single` when absent.
+	count: Option<String>,
 	from: String,
 	to: String,
 }
 
+enum Item {
+	/// A `syntax: regexp` / `syntax: glob` directive, changing how
+	/// subsequent rules' `from` fields are interpreted.
+	Syntax(String),
+	Rule(PRule),
+}
+
 pub struct Rule {
 	pub name: String,
 	pub from: Regex,
 	pub to: Replacement,
+	pub occurrence: Occurrence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+	Regexp,
+	Glob,
+}
+
+impl Syntax {
+	fn compile(self, pattern: &str) -> result::Result<Regex, regex::Error> {
+		match self {
+			Self::Regexp => Regex::new(pattern),
+			Self::Glob => FindPattern::from_str(&format!("glob:{pattern}")).map(|p| p.0),
+		}
+	}
+}
+
+impl FromStr for Syntax {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"regexp" => Ok(Self::Regexp),
+			"glob" => Ok(Self::Glob),
+			other => Err(Error::UnknownSyntax(other.to_owned())),
+		}
+	}
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -25,22 +69,78 @@ pub enum Error {
 	Regex(#[from] regex::Error),
 	#[error("replacement: {0}")]
 	Replacement(#[from] sewer_replacement::Error),
+	#[error("unknown pattern syntax {0:?}, expected \"regexp\" or \"glob\"")]
+	UnknownSyntax(String),
+	#[error("occurrence: {0}")]
+	Occurrence(#[from] crate::occurrence::ParseOccurrenceError),
 }
 type Result<T, E = Error> = result::Result<T, E>;
 
 pub fn parse(input: &str) -> Result<Vec<Rule>> {
-	let prules = patchfile::root(input)?;
+	let items = patchfile::root(input)?;
+	let mut syntax = Syntax::Regexp;
 	let mut out = Vec::new();
-	for prule in prules {
-		out.push(Rule {
-			name: prule.name,
-			from: Regex::new(&prule.from)?,
-			to: Replacement::from_str(&prule.to)?,
-		});
+	for item in items {
+		match item {
+			Item::Syntax(s) => syntax = Syntax::from_str(&s)?,
+			Item::Rule(prule) => {
+				let rule_syntax = match &prule.syntax {
+					Some(s) => Syntax::from_str(s)?,
+					None => syntax,
+				};
+				let occurrence = match &prule.count {
+					Some(c) => Occurrence::from_str(c)?,
+					None => Occurrence::Single,
+				};
+				out.push(Rule {
+					name: prule.name,
+					from: rule_syntax.compile(&prule.from)?,
+					to: Replacement::from_str(&prule.to)?,
+					occurrence,
+				});
+			}
+		}
 	}
 	Ok(out)
 }
 
+/// Split a rule's name line into the name and an optional trailing bracket
+/// of comma-separated overrides, e.g. `"replace magic bytes [glob,count=all]"`
+/// -> `("replace magic bytes", Some("glob"), Some("all"))`. Only brackets
+/// made up entirely of recognised tokens (a syntax name, or `count=<spec>`)
+/// are treated as overrides, so a name that merely ends in brackets (a
+/// version number, a ticket id, ...) is left alone.
+fn split_name_overrides(line: &str) -> (String, Option<String>, Option<String>) {
+	let trimmed = line.trim_end();
+	if let Some(open) = trimmed.rfind('[') {
+		if trimmed.ends_with(']') {
+			let inner = &trimmed[open + 1..trimmed.len() - 1];
+			let tokens: Vec<&str> = inner.split(',').collect();
+			if !tokens.is_empty() && tokens.iter().all(|t| is_override_token(t)) {
+				let mut syntax = None;
+				let mut count = None;
+				for token in tokens {
+					match token.strip_prefix("count=") {
+						Some(value) => count = Some(value.to_owned()),
+						None => syntax = Some(token.to_owned()),
+					}
+				}
+				return (trimmed[..open].trim_end().to_owned(), syntax, count);
+			}
+		}
+	}
+	(line.to_owned(), None, None)
+}
+
+/// Whether a bracket token is a recognised override: a syntax name, or a
+/// `count=<all|N>` occurrence selector.
+fn is_override_token(token: &str) -> bool {
+	match token.strip_prefix("count=") {
+		Some(value) => Occurrence::from_str(value).is_ok(),
+		None => token == "regexp" || token == "glob",
+	}
+}
+
 peg::parser! {
 grammar patchfile() for str {
 rule rest_of_line() -> &'input str
@@ -48,18 +148,99 @@ rule rest_of_line() -> &'input str
 rule prefixed(prefix: rule<()>) -> String
 = v:(prefix() v:rest_of_line() {v})++_ {v.iter().map(|s| s.to_owned()).collect::<Vec<_>>().join("\n")}
 
-pub(super) rule root() -> Vec<PRule>
-= _ rules:prule()**_ _ {rules}
+pub(super) rule root() -> Vec<Item>
+= _ items:item()**_ _ {items}
+
+rule item() -> Item
+= syntax_directive() / prule()
 
-rule prule() -> PRule
-= "#" name:rest_of_line() "\n"
+rule syntax_directive() -> Item
+= "syntax:" [' ' | '\t']* s:rest_of_line() {Item::Syntax(s.trim().to_owned())}
+
+rule prule() -> Item
+= "#" line:rest_of_line() "\n"
 _
 from:prefixed(<"-">)
 _
 to:prefixed(<"+">)
-{PRule {name: name.to_owned(), from, to}}
+{
+	let (name, syntax, count) = split_name_overrides(line);
+	Item::Rule(PRule {name, syntax, count, from, to})
+}
 
 rule _
 = [' ' | '\n' | '\t']*
 }
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_to_regexp_without_a_syntax_directive() {
+		let rules = parse("#rule\n-A.B\n+AxB\n").unwrap();
+		assert_eq!(rules.len(), 1);
+		assert!(rules[0].from.is_match(b"AyB"));
+		assert!(!rules[0].from.is_match(b"AyyB"));
+	}
+
+	#[test]
+	fn syntax_directive_switches_default_for_later_rules() {
+		let rules = parse("syntax: glob\n\n#rule\n-A*B\n+AxB\n").unwrap();
+		assert_eq!(rules.len(), 1);
+		assert!(rules[0].from.is_match(b"AyyyB"));
+		// As a regex, `A*B` would also match a lone `B`.
+		assert!(!rules[0].from.is_match(b"B"));
+	}
+
+	#[test]
+	fn per_rule_syntax_override_beats_the_current_directive() {
+		let rules = parse("syntax: regexp\n\n#rule [glob]\n-A*B\n+AxB\n").unwrap();
+		assert_eq!(rules.len(), 1);
+		assert!(rules[0].from.is_match(b"AyyyB"));
+		assert!(!rules[0].from.is_match(b"B"));
+	}
+
+	#[test]
+	fn per_rule_count_override_sets_occurrence() {
+		let rules = parse("#rule [count=all]\n-A\n+B\n").unwrap();
+		assert_eq!(rules[0].occurrence, Occurrence::All);
+
+		let rules = parse("#rule [count=2]\n-A\n+B\n").unwrap();
+		assert_eq!(rules[0].occurrence, Occurrence::Nth(2));
+
+		let rules = parse("#rule\n-A\n+B\n").unwrap();
+		assert_eq!(rules[0].occurrence, Occurrence::Single);
+	}
+
+	#[test]
+	fn combined_glob_and_count_override() {
+		let rules = parse("#rule [glob,count=all]\n-A*B\n+AxB\n").unwrap();
+		assert_eq!(rules[0].occurrence, Occurrence::All);
+		assert!(rules[0].from.is_match(b"AyyyB"));
+		assert!(!rules[0].from.is_match(b"B"));
+	}
+
+	#[test]
+	fn bracket_that_is_not_a_recognised_override_stays_in_the_name() {
+		let rules = parse("#Bump version [2024]\n-A\n+B\n").unwrap();
+		assert_eq!(rules[0].name, "Bump version [2024]");
+		assert_eq!(rules[0].occurrence, Occurrence::Single);
+	}
+
+	#[test]
+	fn unknown_syntax_directive_is_an_error() {
+		assert!(parse("syntax: nonsense\n\n#rule\n-A\n+B\n").is_err());
+	}
+
+	#[test]
+	fn zero_is_not_a_valid_count_override_so_the_bracket_stays_in_the_name() {
+		// `is_override_token` delegates to `Occurrence::from_str`, so an
+		// invalid count is treated the same as any other non-override
+		// bracket: kept as literal name text rather than rejected.
+		let rules = parse("#rule [count=0]\n-A\n+B\n").unwrap();
+		assert_eq!(rules[0].name, "rule [count=0]");
+		assert_eq!(rules[0].occurrence, Occurrence::Single);
+	}
+}
@@ -0,0 +1,178 @@
+//! Front-end search pattern syntax for `find` expressions.
+//!
+//! A pattern is either `regexp:<pattern>` (also assumed when no prefix is
+//! given, so existing invocations keep working unchanged) or
+//! `glob:<pattern>`, the latter translated to a regex via [`glob_to_re`]
+//! before compilation. This lets binary patches be written with
+//! wildcards instead of hand-escaped regexes. Patchfile `from` fields use
+//! the same glob translation, but take no prefix: their syntax is chosen
+//! by `syntax:` directives and per-rule overrides.
+
+use std::{ops::Deref, str::FromStr};
+
+use regex::bytes::Regex;
+
+/// Bytes that need a `\` prefix when they appear literally in the regex
+/// produced by [`glob_to_re`].
+const SPECIAL: &[u8] = b"()[]{}?*+-|^$\\.&~#\t\n\r\x0b\x0c";
+
+const fn escape_table() -> [bool; 256] {
+	let mut table = [false; 256];
+	let mut i = 0;
+	while i < SPECIAL.len() {
+		table[SPECIAL[i] as usize] = true;
+		i += 1;
+	}
+	table
+}
+pub(crate) static ESCAPE: [bool; 256] = escape_table();
+
+/// Escape a single byte for inclusion in a regex, if it needs one.
+pub(crate) fn push_escaped(out: &mut Vec<u8>, b: u8) {
+	if ESCAPE[b as usize] {
+		out.push(b'\\');
+	}
+	out.push(b);
+}
+
+/// Copy a `[...]` glob character class through to the output regex
+/// unchanged, translating a leading `!` to `^` as regex expects.
+///
+/// `i` must point at the opening `[`; it is left pointing just past the
+/// matching `]` (or at the end of `glob`, if it is unterminated).
+pub(crate) fn copy_char_class(glob: &[u8], i: &mut usize, out: &mut Vec<u8>) {
+	out.push(b'[');
+	*i += 1;
+	if glob.get(*i) == Some(&b'!') {
+		out.push(b'^');
+		*i += 1;
+	}
+	while *i < glob.len() && glob[*i] != b']' {
+		out.push(glob[*i]);
+		*i += 1;
+	}
+	if *i < glob.len() {
+		out.push(b']');
+		*i += 1;
+	}
+}
+
+/// Translate a glob pattern into the equivalent `regex::bytes` source.
+///
+/// `?` matches any single character other than a newline, `*` matches any
+/// run of characters including newlines (non-greedily, since globs are
+/// usually meant to match the shortest plausible span), `**` matches
+/// greedily, `[...]` character classes pass through unchanged (with a
+/// leading `!` translated to `^`), and every other byte is escaped if it is
+/// regex-special. The regex is in Unicode mode, so a character here is a
+/// valid UTF-8 sequence; bytes that are not valid UTF-8 are not matched by
+/// `?`, `*` or `**`.
+pub fn glob_to_re(glob: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(glob.len());
+	let mut i = 0;
+	while i < glob.len() {
+		match glob[i] {
+			b'*' if glob.get(i + 1) == Some(&b'*') => {
+				out.extend_from_slice(br"[\s\S]*");
+				i += 2;
+			}
+			b'*' => {
+				out.extend_from_slice(br"[\s\S]*?");
+				i += 1;
+			}
+			b'?' => {
+				out.push(b'.');
+				i += 1;
+			}
+			b'[' => copy_char_class(glob, &mut i, &mut out),
+			b => {
+				push_escaped(&mut out, b);
+				i += 1;
+			}
+		}
+	}
+	out
+}
+
+/// A search pattern accepted on the command line: either
+/// `regexp:<pattern>` (the default) or `glob:<pattern>`.
+#[derive(Debug, Clone)]
+pub struct FindPattern(pub Regex);
+
+impl Deref for FindPattern {
+	type Target = Regex;
+
+	fn deref(&self) -> &Regex {
+		&self.0
+	}
+}
+
+impl FromStr for FindPattern {
+	type Err = regex::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let regex = if let Some(glob) = s.strip_prefix("glob:") {
+			let translated = glob_to_re(glob.as_bytes());
+			Regex::new(&String::from_utf8_lossy(&translated))?
+		} else {
+			let regexp = s.strip_prefix("regexp:").unwrap_or(s);
+			Regex::new(regexp)?
+		};
+		Ok(Self(regex))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn re(glob: &[u8]) -> Regex {
+		Regex::new(&String::from_utf8_lossy(&glob_to_re(glob))).unwrap()
+	}
+
+	#[test]
+	fn single_star_matches_shortest_run() {
+		assert!(re(b"A*B").is_match(b"AxyzB"));
+		assert!(!re(b"A*B").is_match(b"AxyzC"));
+		assert_eq!(re(b"A*B").find(b"AxBxB").unwrap().range(), 0..3);
+	}
+
+	#[test]
+	fn double_star_spans_newline_bytes() {
+		// A run matched by `**` (or `*`) may contain newline bytes, so it
+		// must not stop short at a 0x0A byte the way `.` would.
+		assert!(re(b"A**B").is_match(b"Axy\nzB"));
+		assert!(re(b"A*B").is_match(b"Axy\nzB"));
+	}
+
+	#[test]
+	fn question_mark_matches_single_byte() {
+		assert!(re(b"A?C").is_match(b"ABC"));
+		assert!(!re(b"A?C").is_match(b"ABBC"));
+	}
+
+	#[test]
+	fn char_class_passes_through_with_negation() {
+		assert!(re(b"[abc]").is_match(b"b"));
+		assert!(re(b"[!abc]").is_match(b"d"));
+		assert!(!re(b"[!abc]").is_match(b"a"));
+	}
+
+	#[test]
+	fn special_bytes_are_escaped() {
+		assert!(re(b"a.b").is_match(b"a.b"));
+		assert!(!re(b"a.b").is_match(b"axb"));
+	}
+
+	#[test]
+	fn find_pattern_defaults_to_regexp() {
+		let p = FindPattern::from_str("A+B").unwrap();
+		assert!(p.0.is_match(b"AAAB"));
+	}
+
+	#[test]
+	fn find_pattern_glob_prefix() {
+		let p = FindPattern::from_str("glob:A*B").unwrap();
+		assert!(p.0.is_match(b"AxyB"));
+	}
+}
@@ -0,0 +1,176 @@
+//! Selecting and walking a tree of files by path pattern, so a patch can be
+//! applied to every file under a root instead of exactly one.
+//!
+//! Patterns follow Mercurial's pattern model: `re:`/`regexp:` for a raw path
+//! regex, `path:` for an exact path, and `glob:`/`rootglob:` for shell-style
+//! globs translated to a path regex (glob: may match starting at any
+//! directory, rootglob: is anchored to the walk root).
+
+use std::{
+	fs, io, result,
+	path::{Path, PathBuf},
+};
+
+use regex::bytes::Regex;
+
+use crate::pattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternSyntax {
+	Regexp,
+	Glob,
+	RootGlob,
+	Path,
+}
+
+/// Split a `--include` pattern into its syntax (defaulting to `Glob` when
+/// no prefix is given, since that is the common case for this option) and
+/// the remaining pattern text.
+pub fn parse(pattern: &str) -> (PatternSyntax, &str) {
+	if let Some(p) = pattern.strip_prefix("rootglob:") {
+		(PatternSyntax::RootGlob, p)
+	} else if let Some(p) = pattern.strip_prefix("glob:") {
+		(PatternSyntax::Glob, p)
+	} else if let Some(p) = pattern.strip_prefix("path:") {
+		(PatternSyntax::Path, p)
+	} else if let Some(p) = pattern.strip_prefix("re:") {
+		(PatternSyntax::Regexp, p)
+	} else if let Some(p) = pattern.strip_prefix("regexp:") {
+		(PatternSyntax::Regexp, p)
+	} else {
+		(PatternSyntax::Glob, pattern)
+	}
+}
+
+impl PatternSyntax {
+	/// Compile a pattern of this syntax into a regex matched against a
+	/// file's path (relative to the walk root, `/`-separated).
+	pub fn compile(self, pattern: &str) -> result::Result<Regex, regex::Error> {
+		match self {
+			Self::Regexp => Regex::new(pattern),
+			Self::RootGlob => {
+				let body = glob_to_path_re(pattern.as_bytes());
+				Regex::new(&format!("^(?:{})(?:/|$)", String::from_utf8_lossy(&body)))
+			}
+			Self::Glob => {
+				let body = glob_to_path_re(pattern.as_bytes());
+				Regex::new(&format!(
+					"(?:^|.*/)(?:{})(?:/|$)",
+					String::from_utf8_lossy(&body)
+				))
+			}
+			Self::Path => Regex::new(&format!("^{}(?:/|$)", regex::escape(pattern))),
+		}
+	}
+}
+
+/// Translate a glob into a path regex: like [`pattern::glob_to_re`], but
+/// `*` only matches within a single path component and `*/` matches any
+/// number of leading directories.
+fn glob_to_path_re(glob: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(glob.len());
+	let mut i = 0;
+	while i < glob.len() {
+		match glob[i] {
+			b'*' if glob.get(i + 1) == Some(&b'*') => {
+				out.extend_from_slice(b".*");
+				i += 2;
+			}
+			b'*' if glob.get(i + 1) == Some(&b'/') => {
+				out.extend_from_slice(br"(?:.*/)?");
+				i += 2;
+			}
+			b'*' => {
+				out.extend_from_slice(br"[^/]*");
+				i += 1;
+			}
+			b'?' => {
+				out.push(b'.');
+				i += 1;
+			}
+			b'[' => pattern::copy_char_class(glob, &mut i, &mut out),
+			b => {
+				pattern::push_escaped(&mut out, b);
+				i += 1;
+			}
+		}
+	}
+	out
+}
+
+/// Recursively collect every file under `root` whose path, relative to
+/// `root`, matches `pattern`.
+pub fn walk(root: &Path, pattern: &Regex) -> io::Result<Vec<PathBuf>> {
+	let mut out = Vec::new();
+	walk_into(root, root, pattern, &mut out)?;
+	Ok(out)
+}
+
+fn walk_into(root: &Path, dir: &Path, pattern: &Regex, out: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let path = entry.path();
+		let file_type = entry.file_type()?;
+
+		if file_type.is_dir() {
+			walk_into(root, &path, pattern, out)?;
+			continue;
+		}
+		if !file_type.is_file() {
+			continue;
+		}
+
+		let relative = path.strip_prefix(root).expect("child of root");
+		if pattern.is_match(relative.to_string_lossy().as_bytes()) {
+			out.push(path);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn matches(syntax: PatternSyntax, pattern: &str, path: &str) -> bool {
+		syntax.compile(pattern).unwrap().is_match(path.as_bytes())
+	}
+
+	#[test]
+	fn glob_matches_starting_at_any_directory() {
+		assert!(matches(PatternSyntax::Glob, "*.bin", "a.bin"));
+		assert!(matches(PatternSyntax::Glob, "*.bin", "sub/dir/a.bin"));
+		assert!(!matches(PatternSyntax::Glob, "*.bin", "a.bin.old"));
+	}
+
+	#[test]
+	fn rootglob_is_anchored_to_the_walk_root() {
+		assert!(matches(PatternSyntax::RootGlob, "sub/*.bin", "sub/a.bin"));
+		assert!(!matches(PatternSyntax::RootGlob, "sub/*.bin", "other/sub/a.bin"));
+	}
+
+	#[test]
+	fn glob_star_does_not_cross_a_path_separator() {
+		assert!(matches(PatternSyntax::Glob, "sub/*.bin", "sub/a.bin"));
+		assert!(!matches(PatternSyntax::Glob, "sub/*.bin", "sub/nested/a.bin"));
+	}
+
+	#[test]
+	fn glob_double_star_crosses_path_separators() {
+		assert!(matches(PatternSyntax::Glob, "sub/**/a.bin", "sub/x/y/a.bin"));
+	}
+
+	#[test]
+	fn path_syntax_matches_literal_path_or_its_subtree() {
+		assert!(matches(PatternSyntax::Path, "sub/dir", "sub/dir"));
+		assert!(matches(PatternSyntax::Path, "sub/dir", "sub/dir/a.bin"));
+		assert!(!matches(PatternSyntax::Path, "sub/dir", "sub/dir2"));
+	}
+
+	#[test]
+	fn parse_defaults_to_glob_without_a_prefix() {
+		assert_eq!(parse("*.bin"), (PatternSyntax::Glob, "*.bin"));
+		assert_eq!(parse("rootglob:*.bin"), (PatternSyntax::RootGlob, "*.bin"));
+		assert_eq!(parse("re:^a"), (PatternSyntax::Regexp, "^a"));
+	}
+}